@@ -9,8 +9,10 @@ use std::mem::size_of;
 use std::path::Path;
 
 pub mod pager;
+pub mod varint;
 
-use pager::{MemPage, Pager, HEADER_SIZE, PAGE_SIZE};
+use pager::{MemPage, Pager, DEFAULT_PAGE_SIZE, HEADER_SIZE};
+use varint::{parse_varint, varint_size, write_varint};
 
 #[derive(PartialEq)]
 pub enum ChiError {
@@ -26,6 +28,9 @@ pub enum ChiError {
     /// Could not allocate memory
     Enomem,
 
+    /// The node does not have enough free space to store a new cell
+    Enospace,
+
     /// An I/O error
     IO(io::ErrorKind),
 }
@@ -37,6 +42,7 @@ impl std::fmt::Debug for ChiError {
             ChiError::NoHeader => write!(f, "file does not have a header"),
             ChiError::Ecorruptheader => write!(f, "invalid database header"),
             ChiError::Enomem => write!(f, "could not allocate memory"),
+            ChiError::Enospace => write!(f, "not enough free space in node for cell"),
             ChiError::IO(err) => write!(f, "{:?}", err),
         }
     }
@@ -64,23 +70,29 @@ impl BTree {
     /// if the pager is given a filename for a file that does not exist)
     /// then this function will (1) initialize the file header using
     /// the default page size and (2) create an empty table leaf node
-    /// in page 1.
+    /// in page 1. If a rollback journal is left over from a transaction
+    /// that never committed (e.g. a crash), it is replayed and discarded
+    /// before the header is validated.
     ///
     /// Parameters
     /// - filename: Database file (might not exist)
     pub fn open(filename: &Path) -> Result<Self, ChiError> {
-        let pager = Pager::open(filename)?;
+        let mut pager = Pager::open(filename)?;
+        pager.recover_from_journal()?;
+        let mut btree = BTree { pager };
 
-        if pager.is_empty()? {
-            let mut btree = BTree { pager };
+        if btree.pager.is_empty()? {
             btree.initialize_header()?;
             btree.initialize_empty_table_leaf()?;
-            Ok(btree)
         } else {
-            let mut btree = BTree { pager };
             btree.validate_header()?;
-            Ok(btree)
         }
+
+        let header = btree.read_header()?;
+        let cache_pages = (header.page_cache_size as usize / btree.pager.page_size()).max(1);
+        btree.pager.set_cache_capacity(cache_pages);
+
+        Ok(btree)
     }
 
     /// Loads a B-Tree node from disk
@@ -120,28 +132,304 @@ impl BTree {
     /// the in-memory page according to the chidb page format. Since the cell
     /// offset array and the cells themselves are modified directly on the
     /// page, the only thing to do is to store the values of "type",
-    /// "free_offset", "n_cells", "cells_offset" and "right_page" in the
-    /// in-memory page.
+    /// "free_offset", "n_cells", "cells_offset", "right_page",
+    /// "first_freeblock" and "fragmented_free_bytes" in the in-memory page.
     ///
     /// Parameters
     /// - node: BTreeNode to write to disk
     pub fn write_node(&mut self, node: &mut BTreeNode) -> Result<(), ChiError> {
-        let page_data = node.page.data();
-        let bytes = BytesMut::with_capacity(page_data.len());
-        let mut buffer = BufWriter::with_capacity(page_data.len(), bytes.writer());
+        let data = node.page.data_as_mut();
 
-        buffer.write(&[node.typ.value()])?;
-        buffer.write(&node.free_offset.to_le_bytes())?;
-        buffer.write(&node.n_cells.to_le_bytes())?;
-        buffer.write(&node.cells_offset.to_le_bytes())?;
-        buffer.write(&node.right_page.to_le_bytes())?;
+        data[0] = node.typ.value();
+        data[1..3].copy_from_slice(&node.free_offset.to_le_bytes());
+        data[3..5].copy_from_slice(&node.n_cells.to_le_bytes());
+        data[5..7].copy_from_slice(&node.cells_offset.to_le_bytes());
+        data[7..11].copy_from_slice(&node.right_page.to_le_bytes());
+        data[11..13].copy_from_slice(&node.first_freeblock.to_le_bytes());
+        data[13] = node.fragmented_free_bytes;
 
-        node.page.set_data(buffer.buffer());
         self.pager.write_page(&node.page)?;
 
         Ok(())
     }
 
+    /// Begin a transaction: from this point on, the first modification to
+    /// any page has its pre-transaction contents recorded in a rollback
+    /// journal, so the transaction can be undone by `rollback`. Calling this
+    /// while already in a transaction is a no-op.
+    pub fn begin(&mut self) -> Result<(), ChiError> {
+        self.pager.begin_transaction()
+    }
+
+    /// Make the current transaction durable: bump the header's
+    /// `file_change_counter`, fsync the database file, and discard the
+    /// rollback journal.
+    pub fn commit(&mut self) -> Result<(), ChiError> {
+        // Flush dirty pages before bumping the header. Page 1 physically
+        // carries the header bytes ahead of its own node content, so
+        // `Pager::write_header` keeps any cached copy of page 1 in sync
+        // with what it writes to disk -- otherwise a later flush of that
+        // cached page would silently overwrite the new counter with a
+        // stale one.
+        self.pager.flush()?;
+        self.increment_file_change_counter()?;
+        self.pager.commit()
+    }
+
+    /// Undo every page modified since `begin` was called, restoring them to
+    /// their pre-transaction contents, and discard the rollback journal.
+    pub fn rollback(&mut self) -> Result<(), ChiError> {
+        self.pager.rollback()
+    }
+
+    fn increment_file_change_counter(&mut self) -> Result<(), ChiError> {
+        let mut header = self.read_header()?;
+        header.file_change_counter += 1;
+        let bytes = header.to_bytes()?;
+        self.pager.write_header(&bytes)
+    }
+
+    /// Insert a key/payload pair into the table rooted at page `ROOT_PAGE`.
+    ///
+    /// Descends to the target leaf via `find_leaf`, then inserts directly if
+    /// the leaf has room. Otherwise the leaf is split: its cells (plus the
+    /// new one) are divided in half, the upper half moves to a freshly
+    /// allocated page, and the median key is promoted as a separator cell
+    /// into the parent. If the leaf being split is the root itself, page 1
+    /// must remain the root, so its content is relocated to a new page and
+    /// page 1 is rewritten as the new `InternalTable` root.
+    ///
+    /// Splitting an internal node that is itself full is not supported yet;
+    /// promoting a separator into a full parent returns `ChiError::Enospace`.
+    ///
+    /// The whole operation, including any leaf split and overflow page
+    /// allocation, runs inside its own transaction: on success it's
+    /// committed, on failure it's rolled back, so a crash or an error never
+    /// leaves the tree half-split.
+    pub fn insert(&mut self, key: u64, payload: &[u8]) -> Result<(), ChiError> {
+        self.begin()?;
+        match self.insert_in_transaction(key, payload) {
+            Ok(()) => self.commit(),
+            Err(err) => {
+                self.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    fn insert_in_transaction(&mut self, key: u64, payload: &[u8]) -> Result<(), ChiError> {
+        let (total_len, local_bytes) = self.store_payload(payload)?;
+
+        let path = self.find_leaf_path(key)?;
+        let leaf_page = *path.last().expect("find_leaf_path never returns an empty path");
+        let mut leaf = self.get_node_by_page(leaf_page)?;
+
+        let needed = cell_header_size(total_len as u64, key) as u16 + local_bytes.len() as u16 + POINTER_SIZE;
+        if leaf.compute_free_size() >= needed {
+            leaf.insert_cell_raw(key, total_len, &local_bytes)?;
+            return self.write_node(&mut leaf);
+        }
+
+        self.split_leaf_and_insert(&path, leaf, key, total_len, &local_bytes)
+    }
+
+    /// Reassemble the full logical payload for the cell at `index` in
+    /// `node`, following its overflow chain (see `store_payload`) if the
+    /// payload didn't fit inline.
+    pub fn read_cell_payload(&mut self, node: &BTreeNode, index: u16) -> Result<Vec<u8>, ChiError> {
+        let total_len = node.cell_total_len(index) as usize;
+        let cell = node.get_cell(index);
+        let max_inline_payload = max_inline_payload(self.page_size());
+
+        if total_len <= max_inline_payload {
+            return Ok(cell.payload);
+        }
+
+        let prefix_len = max_inline_payload - OVERFLOW_POINTER_SIZE;
+        let mut first_page_bytes = [0; OVERFLOW_POINTER_SIZE];
+        first_page_bytes.copy_from_slice(&cell.payload[prefix_len..]);
+        let first_page = u32::from_be_bytes(first_page_bytes);
+
+        let mut payload = cell.payload[..prefix_len].to_vec();
+        payload.extend_from_slice(&self.read_overflow_chain(first_page, total_len - prefix_len)?);
+        Ok(payload)
+    }
+
+    /// Split `payload` into a cell's on-page representation: if it fits
+    /// within `max_inline_payload(page_size)` it's returned as-is, otherwise an inline
+    /// prefix is kept and the remainder is written to a new overflow page
+    /// chain (see `write_overflow_chain`), with the chain's head page number
+    /// appended after the prefix. Returns the true logical length alongside
+    /// the bytes to store in the cell.
+    fn store_payload(&mut self, payload: &[u8]) -> Result<(u32, Vec<u8>), ChiError> {
+        let max_inline_payload = max_inline_payload(self.page_size());
+        if payload.len() <= max_inline_payload {
+            return Ok((payload.len() as u32, payload.to_vec()));
+        }
+
+        let prefix_len = max_inline_payload - OVERFLOW_POINTER_SIZE;
+        let first_page = self.write_overflow_chain(&payload[prefix_len..])?;
+
+        let mut local_bytes = payload[..prefix_len].to_vec();
+        local_bytes.extend_from_slice(&first_page.to_be_bytes());
+
+        Ok((payload.len() as u32, local_bytes))
+    }
+
+    /// Write `payload` across a chain of newly allocated overflow pages,
+    /// each beginning with a 4-byte big-endian pointer to the next page (0
+    /// terminates the chain) followed by payload bytes. Returns the page
+    /// number of the chain's head.
+    fn write_overflow_chain(&mut self, payload: &[u8]) -> Result<u32, ChiError> {
+        let page_size = self.page_size();
+        let mut next_page: u32 = 0;
+        for chunk in payload.chunks(overflow_page_capacity(page_size)).rev() {
+            let n_page = self.pager.allocate_page();
+
+            let mut data = vec![0u8; page_size];
+            data[..OVERFLOW_POINTER_SIZE].copy_from_slice(&next_page.to_be_bytes());
+            data[OVERFLOW_POINTER_SIZE..OVERFLOW_POINTER_SIZE + chunk.len()].copy_from_slice(chunk);
+
+            self.pager.write_page(&MemPage::new(n_page, &data, 0))?;
+            next_page = n_page;
+        }
+
+        Ok(next_page)
+    }
+
+    /// Read `total_len` payload bytes starting at overflow page
+    /// `first_page`, following each page's next-page pointer until it
+    /// terminates at 0.
+    fn read_overflow_chain(&mut self, first_page: u32, total_len: usize) -> Result<Vec<u8>, ChiError> {
+        let overflow_page_capacity = overflow_page_capacity(self.page_size());
+        let mut payload = Vec::with_capacity(total_len);
+        let mut remaining = total_len;
+        let mut page_no = first_page;
+
+        while remaining > 0 && page_no != 0 {
+            let page = self.pager.read_page(page_no)?;
+            let data = page.data();
+
+            let mut next_bytes = [0; OVERFLOW_POINTER_SIZE];
+            next_bytes.copy_from_slice(&data[..OVERFLOW_POINTER_SIZE]);
+            let next_page = u32::from_be_bytes(next_bytes);
+
+            let take = remaining.min(overflow_page_capacity);
+            payload.extend_from_slice(&data[OVERFLOW_POINTER_SIZE..OVERFLOW_POINTER_SIZE + take]);
+
+            remaining -= take;
+            page_no = next_page;
+        }
+
+        Ok(payload)
+    }
+
+    /// Descend from the root to the leaf that should contain `key`.
+    ///
+    /// At each `InternalTable` node, binary-search the cell keys to pick the
+    /// child pointer, falling through to `right_page` when `key` exceeds
+    /// every separator.
+    pub fn find_leaf(&mut self, key: u64) -> Result<u32, ChiError> {
+        let path = self.find_leaf_path(key)?;
+        Ok(*path.last().expect("find_leaf_path never returns an empty path"))
+    }
+
+    /// Like `find_leaf`, but also returns every internal node visited along
+    /// the way (root first), so callers can promote a separator into the
+    /// immediate parent after a leaf split.
+    fn find_leaf_path(&mut self, key: u64) -> Result<Vec<u32>, ChiError> {
+        let mut path = vec![ROOT_PAGE];
+        loop {
+            let current = *path.last().expect("path is never empty");
+            let node = self.get_node_by_page(current)?;
+            match node.typ {
+                BTreeNodeType::LeafTable => return Ok(path),
+                BTreeNodeType::InternalTable => path.push(node.find_child(key)),
+                _ => return Err(ChiError::Ecorruptheader),
+            }
+        }
+    }
+
+    /// Split a full leaf and insert `key`/`local_bytes` (the on-page
+    /// representation produced by `store_payload`, logically `total_len`
+    /// bytes long) into the resulting tree.
+    ///
+    /// `path` is the root-to-leaf path returned by `find_leaf_path`; `leaf`
+    /// is the already-loaded node at `path`'s last entry. Existing cells are
+    /// redistributed by their stored bytes, not their reassembled payload,
+    /// so overflow chains are left untouched by the split.
+    fn split_leaf_and_insert(
+        &mut self,
+        path: &[u32],
+        leaf: BTreeNode,
+        key: u64,
+        total_len: u32,
+        local_bytes: &[u8],
+    ) -> Result<(), ChiError> {
+        let leaf_page = leaf.page.n_page;
+        let is_root = path.len() == 1;
+
+        let mut cells: Vec<(u64, u32, Vec<u8>)> = (0..leaf.n_cells)
+            .map(|i| (leaf.cell_key_at(i as usize), leaf.cell_total_len(i), leaf.get_cell(i).payload))
+            .collect();
+        let insert_at = cells.partition_point(|(k, _, _)| *k < key);
+        cells.insert(insert_at, (key, total_len, local_bytes.to_vec()));
+
+        let mid = cells.len() / 2;
+        let right_cells = cells.split_off(mid);
+        let left_cells = cells;
+        let separator_key = right_cells[0].0;
+
+        let mut right_node = self.new_node(BTreeNodeType::LeafTable)?;
+        for (k, t, b) in &right_cells {
+            right_node.insert_cell_raw(*k, *t, b)?;
+        }
+        self.write_node(&mut right_node)?;
+        let right_page = right_node.page.n_page;
+
+        if is_root {
+            // page 1 must remain the root, so the old root's content moves
+            // to a new page and page 1 becomes the InternalTable root.
+            let mut left_node = self.new_node(BTreeNodeType::LeafTable)?;
+            for (k, t, b) in &left_cells {
+                left_node.insert_cell_raw(*k, *t, b)?;
+            }
+            self.write_node(&mut left_node)?;
+            let left_page = left_node.page.n_page;
+
+            let mut new_root = BTreeNode::create(leaf.page, BTreeNodeType::InternalTable)?;
+            new_root.insert_cell(separator_key, &left_page.to_be_bytes())?;
+            new_root.right_page = right_page;
+            return self.write_node(&mut new_root);
+        }
+
+        // Non-root split: rewrite the original leaf page in place with the
+        // lower half, then promote the separator into the parent.
+        let mut left_node = BTreeNode::create(leaf.page, BTreeNodeType::LeafTable)?;
+        for (k, t, b) in &left_cells {
+            left_node.insert_cell_raw(*k, *t, b)?;
+        }
+        self.write_node(&mut left_node)?;
+
+        let parent_page = path[path.len() - 2];
+        let mut parent = self.get_node_by_page(parent_page)?;
+
+        // leaf_page now only holds the lower half of what it used to; the
+        // parent's existing pointer into it (right_page if leaf_page was its
+        // rightmost child, otherwise a sibling cell's child field) has to be
+        // repointed at right_node, which took over the upper half.
+        if parent.right_page == leaf_page {
+            parent.right_page = right_page;
+        } else {
+            let child_index = (0..parent.n_cells)
+                .find(|&i| parent.child_at(i) == leaf_page)
+                .expect("leaf_page must be reachable from its own parent");
+            parent.set_child_pointer(child_index, right_page);
+        }
+        parent.insert_cell(separator_key, &leaf_page.to_be_bytes())?;
+        self.write_node(&mut parent)
+    }
+
     fn validate_header(&mut self) -> Result<(), ChiError> {
         let header = self.read_header()?;
         if MAGIC_BYTES.clone() != header.magic_bytes {
@@ -161,7 +449,10 @@ impl BTree {
     }
 
     fn initialize_header(&mut self) -> Result<(), ChiError> {
-        let header = BTreeHeader::default();
+        let header = BTreeHeader {
+            page_size: self.pager.page_size() as u16,
+            ..Default::default()
+        };
         let mut header_bytes = self.pager.read_header()?;
 
         let raw = &mut header_bytes[0..HEADER_SIZE];
@@ -180,6 +471,11 @@ impl BTree {
         let header = BTreeHeader::from_bytes(&header_bytes)?;
         Ok(header)
     }
+
+    /// Size, in bytes, of every page in this database.
+    fn page_size(&self) -> usize {
+        self.pager.page_size()
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -243,22 +539,107 @@ pub struct BTreeNode {
     cells_offset: u16,
 
     /// Right page (internal nodes only)
-    right_page: u16,
+    right_page: u32,
+
+    /// Byte offset of the first freeblock in the page, or 0 if there are none.
+    /// Each freeblock is a 4-byte record (2-byte offset of the next freeblock,
+    /// 2-byte size) occupying reclaimed cell space.
+    first_freeblock: u16,
+
+    /// Bytes of free space left behind by deletes that are too small (< 4
+    /// bytes) to track as a freeblock.
+    fragmented_free_bytes: u8,
 
-    /// Pointer to start of cell offset array in the in-memory page
+    /// Pointer to start of cell offset array in the in-memory page. This is
+    /// always `PAGE_HEADER_SIZE`; it is not persisted to disk.
     celloffset_array: u8,
 }
 
+/// Size, in bytes, of a node's fixed header: type (1) + free_offset (2) +
+/// n_cells (2) + cells_offset (2) + right_page (4) + first_freeblock (2) +
+/// fragmented_free_bytes (1). The cell offset array always starts
+/// immediately after it.
+pub const PAGE_HEADER_SIZE: u8 = 14;
+
+/// Minimum size, in bytes, of a chunk that can be tracked as a freeblock.
+/// Reclaimed space smaller than this is folded into `fragmented_free_bytes`
+/// instead.
+const MIN_FREEBLOCK_SIZE: u16 = 4;
+
+/// Page number of a table's root node. It never moves: when the root splits,
+/// its content is relocated and page 1 is rewritten as the new root instead.
+const ROOT_PAGE: u32 = 1;
+
+/// Size, in bytes, of a cell's header: a varint-encoded total payload
+/// length followed by a varint-encoded key. Unlike the node's own header,
+/// this varies per cell, so callers must compute it from the values being
+/// stored rather than treating it as a constant.
+fn cell_header_size(total_len: u64, key: u64) -> usize {
+    varint_size(total_len) + varint_size(key)
+}
+
+/// Size, in bytes, of a single entry in the cell offset array.
+const POINTER_SIZE: u16 = 2;
+
+/// Maximum number of payload bytes stored inline in a cell. Payloads longer
+/// than this keep only a prefix inline and spill the remainder into a chain
+/// of overflow pages (see `BTree::store_payload`). Expressed as a fraction
+/// of the database's page size so it scales consistently across databases
+/// with different page sizes, and so the same threshold applies to every
+/// node type.
+fn max_inline_payload(page_size: usize) -> usize {
+    page_size / 4
+}
+
+/// Size, in bytes, of the "next overflow page" pointer stored at the start
+/// of every overflow page, and of the pointer an overflowing cell keeps
+/// inline in place of its last few payload bytes.
+const OVERFLOW_POINTER_SIZE: usize = 4;
+
+/// Number of payload bytes an overflow page can hold: the whole page minus
+/// its next-page pointer.
+fn overflow_page_capacity(page_size: usize) -> usize {
+    page_size - OVERFLOW_POINTER_SIZE
+}
+
+/// Number of payload bytes physically stored in a cell for a payload whose
+/// logical length is `total_len`: the full payload when it fits inline, or
+/// `max_inline_payload(page_size)` (an inline prefix plus a trailing
+/// overflow pointer) when it doesn't.
+fn local_payload_size(total_len: usize, page_size: usize) -> usize {
+    total_len.min(max_inline_payload(page_size))
+}
+
+/// An in-memory view of a cell stored in a `BTreeNode`.
+///
+/// `payload` holds only the bytes physically stored on this page: the full
+/// payload when it fits inline, or an inline prefix followed by a 4-byte
+/// overflow page pointer when it doesn't. Use `BTree::read_cell_payload` to
+/// reassemble the full payload for a cell that may have overflowed.
+#[derive(Debug, PartialEq)]
+pub struct Cell {
+    pub key: u64,
+    pub payload: Vec<u8>,
+}
+
 impl BTreeNode {
     pub fn new(page: MemPage, typ: BTreeNodeType) -> Self {
+        // Cells are allocated from the tail of this page's *usable* space,
+        // which on page 1 is shorter than the full page size because the file header
+        // occupies the start of it.
+        let cells_offset = page.data().len() as u16;
         BTreeNode {
             page,
             typ,
-            free_offset: 0,
+            // free_offset marks where the unallocated gap starts, i.e. right
+            // after the (currently empty) cell offset array.
+            free_offset: PAGE_HEADER_SIZE as u16,
             n_cells: 0,
-            cells_offset: PAGE_SIZE as u16,
+            cells_offset,
             right_page: 0,
-            celloffset_array: 0,
+            first_freeblock: 0,
+            fragmented_free_bytes: 0,
+            celloffset_array: PAGE_HEADER_SIZE,
         }
     }
 
@@ -280,7 +661,8 @@ impl BTreeNode {
         bytes_writen += buffer.write(&node.n_cells.to_le_bytes())?;
         bytes_writen += buffer.write(&node.cells_offset.to_le_bytes())?;
         bytes_writen += buffer.write(&node.right_page.to_le_bytes())?;
-        bytes_writen += buffer.write(&node.celloffset_array.to_le_bytes())?;
+        bytes_writen += buffer.write(&node.first_freeblock.to_le_bytes())?;
+        bytes_writen += buffer.write(&node.fragmented_free_bytes.to_le_bytes())?;
 
         let empty_space = vec![0; page_len - bytes_writen];
         buffer.write(&empty_space)?;
@@ -299,15 +681,17 @@ impl BTreeNode {
         let mut free_offset = [0; size_of::<u16>()];
         let mut n_cells = [0; size_of::<u16>()];
         let mut cells_offset = [0; size_of::<u16>()];
-        let mut righ_page = [0; size_of::<u16>()];
-        let mut celloffset_array = [0; size_of::<u8>()];
+        let mut righ_page = [0; size_of::<u32>()];
+        let mut first_freeblock = [0; size_of::<u16>()];
+        let mut fragmented_free_bytes = [0; size_of::<u8>()];
 
         buffer.read(&mut typ)?;
         buffer.read(&mut free_offset)?;
         buffer.read(&mut n_cells)?;
         buffer.read(&mut cells_offset)?;
         buffer.read(&mut righ_page)?;
-        buffer.read(&mut celloffset_array)?;
+        buffer.read(&mut first_freeblock)?;
+        buffer.read(&mut fragmented_free_bytes)?;
 
         Ok(BTreeNode {
             page,
@@ -315,10 +699,326 @@ impl BTreeNode {
             free_offset: u16::from_le_bytes(free_offset),
             n_cells: u16::from_le_bytes(n_cells),
             cells_offset: u16::from_le_bytes(cells_offset),
-            right_page: u16::from_le_bytes(righ_page),
-            celloffset_array: u8::from_le_bytes(celloffset_array),
+            right_page: u32::from_le_bytes(righ_page),
+            first_freeblock: u16::from_le_bytes(first_freeblock),
+            fragmented_free_bytes: u8::from_le_bytes(fragmented_free_bytes),
+            celloffset_array: PAGE_HEADER_SIZE,
         })
     }
+
+    /// Insert a new cell into the node, keeping the cell offset array sorted
+    /// by key.
+    ///
+    /// `payload` is stored inline in full; use `BTree::insert` instead for
+    /// payloads that may need to overflow onto a page chain, since this
+    /// node-level function has no access to the pager and simply rejects,
+    /// with `ChiError::Enospace`, a payload longer than `max_inline_payload`
+    /// or too large to fit in the node.
+    pub fn insert_cell(&mut self, key: u64, payload: &[u8]) -> Result<(), ChiError> {
+        if payload.len() > max_inline_payload(self.page.raw().len()) {
+            return Err(ChiError::Enospace);
+        }
+        self.insert_cell_raw(key, payload.len() as u32, payload)
+    }
+
+    /// Like `insert_cell`, but lets the caller store a logical length
+    /// greater than `local_bytes.len()`. Used for overflow cells, where
+    /// `local_bytes` is an inline prefix followed by a 4-byte overflow page
+    /// pointer and `total_len` is the full payload length reassembled by
+    /// `BTree::read_cell_payload`.
+    ///
+    /// Cell content is first carved out of the freeblock list (see
+    /// `allocate_from_freeblocks`) so that deletes followed by inserts don't
+    /// permanently grow the page; only when no freeblock is big enough does
+    /// it fall back to the tail of the page, growing downward from
+    /// `cells_offset` toward the header. The new 2-byte pointer is inserted
+    /// into the offset array, which keeps growing upward from
+    /// `celloffset_array`. Returns `ChiError::Enospace` if the unallocated
+    /// gap between `free_offset` and `cells_offset` cannot hold the pointer
+    /// entry, or the cell content when no freeblock fits it either.
+    fn insert_cell_raw(&mut self, key: u64, total_len: u32, local_bytes: &[u8]) -> Result<(), ChiError> {
+        let header_size = cell_header_size(total_len as u64, key);
+        let cell_size = header_size + local_bytes.len();
+        let gap = self.cells_offset.saturating_sub(self.free_offset) as usize;
+        if gap < POINTER_SIZE as usize {
+            return Err(ChiError::Enospace);
+        }
+
+        let cell_start = match self.allocate_from_freeblocks(cell_size as u16) {
+            Some(offset) => offset,
+            None => {
+                if gap - (POINTER_SIZE as usize) < cell_size {
+                    return Err(ChiError::Enospace);
+                }
+                let new_cells_offset = self.cells_offset - cell_size as u16;
+                self.cells_offset = new_cells_offset;
+                new_cells_offset
+            }
+        };
+
+        let index = self.find_insert_index(key);
+
+        let data = self.page.data_as_mut();
+
+        let start = cell_start as usize;
+        let mut header = [0u8; 18];
+        let n1 = write_varint(&mut header, total_len as u64);
+        let n2 = write_varint(&mut header[n1..], key);
+        debug_assert_eq!(n1 + n2, header_size);
+        data[start..start + header_size].copy_from_slice(&header[..header_size]);
+        data[start + header_size..start + cell_size].copy_from_slice(local_bytes);
+
+        // Make room in the offset array for the new pointer, keeping it sorted by key.
+        let array_start = self.celloffset_array as usize;
+        let insert_at = array_start + index * POINTER_SIZE as usize;
+        let tail_len = (self.n_cells as usize - index) * POINTER_SIZE as usize;
+        data.copy_within(insert_at..insert_at + tail_len, insert_at + POINTER_SIZE as usize);
+        data[insert_at..insert_at + 2].copy_from_slice(&cell_start.to_be_bytes());
+
+        self.n_cells += 1;
+        self.free_offset += POINTER_SIZE;
+
+        Ok(())
+    }
+
+    /// Remove the cell at `index`, reclaiming its content space as a
+    /// freeblock (or folding it into `fragmented_free_bytes` if it's too
+    /// small to track).
+    ///
+    /// Panics if `index` is out of bounds, for the same reason as `get_cell`.
+    pub fn delete_cell(&mut self, index: u16) -> Result<(), ChiError> {
+        let idx = index as usize;
+        assert!(idx < self.n_cells as usize, "cell index out of bounds");
+
+        let offset = self.pointer_at(idx);
+        let cell_size = {
+            let (total_len, _, header_size) = self.parse_cell_header(offset as usize);
+            header_size + local_payload_size(total_len as usize, self.page.raw().len())
+        };
+
+        // Remove the pointer entry, shifting later entries down to close the gap.
+        let array_start = self.celloffset_array as usize;
+        let remove_at = array_start + idx * POINTER_SIZE as usize;
+        let tail_len = (self.n_cells as usize - idx - 1) * POINTER_SIZE as usize;
+        let data = self.page.data_as_mut();
+        data.copy_within(
+            remove_at + POINTER_SIZE as usize..remove_at + POINTER_SIZE as usize + tail_len,
+            remove_at,
+        );
+
+        self.n_cells -= 1;
+        self.free_offset -= POINTER_SIZE;
+
+        self.free_cell_space(offset, cell_size as u16);
+
+        Ok(())
+    }
+
+    /// Total free space available for new cells: the unallocated gap between
+    /// `free_offset` and `cells_offset`, plus everything reclaimed in the
+    /// freeblock list, plus leftover fragmented bytes.
+    pub fn compute_free_size(&self) -> u16 {
+        let gap = self.cells_offset.saturating_sub(self.free_offset);
+
+        let mut freeblocks_size = 0u16;
+        let mut current = self.first_freeblock;
+        while current != 0 {
+            let (next, size) = self.read_freeblock(current);
+            freeblocks_size += size;
+            current = next;
+        }
+
+        gap + freeblocks_size + self.fragmented_free_bytes as u16
+    }
+
+    /// Walk the freeblock list first-fit, looking for a chunk at least
+    /// `size` bytes long. On a hit, splits off the remainder as a new
+    /// freeblock if it's at least `MIN_FREEBLOCK_SIZE`, or folds it into
+    /// `fragmented_free_bytes` otherwise, and returns the offset of the
+    /// allocated chunk. Returns `None` if no freeblock is big enough.
+    fn allocate_from_freeblocks(&mut self, size: u16) -> Option<u16> {
+        let mut prev: Option<u16> = None;
+        let mut current = self.first_freeblock;
+
+        while current != 0 {
+            let (next, block_size) = self.read_freeblock(current);
+
+            if block_size >= size {
+                let remainder = block_size - size;
+                if remainder >= MIN_FREEBLOCK_SIZE {
+                    let new_block = current + size;
+                    self.write_freeblock(new_block, next, remainder);
+                    self.set_next_freeblock(prev, new_block);
+                } else {
+                    self.fragmented_free_bytes =
+                        self.fragmented_free_bytes.saturating_add(remainder as u8);
+                    self.set_next_freeblock(prev, next);
+                }
+                return Some(current);
+            }
+
+            prev = Some(current);
+            current = next;
+        }
+
+        None
+    }
+
+    /// Add `[offset, offset + size)` back to the freeblock list, or fold it
+    /// into `fragmented_free_bytes` if it's smaller than `MIN_FREEBLOCK_SIZE`.
+    fn free_cell_space(&mut self, offset: u16, size: u16) {
+        if size < MIN_FREEBLOCK_SIZE {
+            self.fragmented_free_bytes = self.fragmented_free_bytes.saturating_add(size as u8);
+            return;
+        }
+
+        let head = self.first_freeblock;
+        self.write_freeblock(offset, head, size);
+        self.first_freeblock = offset;
+    }
+
+    /// Point `prev`'s freeblock (or `first_freeblock` if `prev` is `None`)
+    /// at `next`.
+    fn set_next_freeblock(&mut self, prev: Option<u16>, next: u16) {
+        match prev {
+            Some(offset) => {
+                let data = self.page.data_as_mut();
+                let start = offset as usize;
+                data[start..start + 2].copy_from_slice(&next.to_be_bytes());
+            }
+            None => self.first_freeblock = next,
+        }
+    }
+
+    fn read_freeblock(&self, offset: u16) -> (u16, u16) {
+        let data = self.page.data();
+        let start = offset as usize;
+
+        let mut next = [0; 2];
+        next.copy_from_slice(&data[start..start + 2]);
+
+        let mut size = [0; 2];
+        size.copy_from_slice(&data[start + 2..start + 4]);
+
+        (u16::from_be_bytes(next), u16::from_be_bytes(size))
+    }
+
+    fn write_freeblock(&mut self, offset: u16, next: u16, size: u16) {
+        let data = self.page.data_as_mut();
+        let start = offset as usize;
+        data[start..start + 2].copy_from_slice(&next.to_be_bytes());
+        data[start + 2..start + 4].copy_from_slice(&size.to_be_bytes());
+    }
+
+    /// Read the cell stored at `index` in the cell offset array.
+    ///
+    /// Panics if `index` is out of bounds; indices only ever come from this
+    /// node's own offset array, so an out-of-range index is a programmer
+    /// error rather than something to propagate as a `ChiError`.
+    pub fn get_cell(&self, index: u16) -> Cell {
+        let offset = self.pointer_at(index as usize) as usize;
+        let data = self.page.data();
+
+        let (total_len, key, header_size) = self.parse_cell_header(offset);
+        let local_len = local_payload_size(total_len as usize, self.page.raw().len());
+
+        let payload_start = offset + header_size;
+        let payload = data[payload_start..payload_start + local_len].to_vec();
+
+        Cell { key, payload }
+    }
+
+    /// Parse the varint-encoded header (total payload length, then key) of
+    /// the cell stored at `offset`, returning the decoded values along with
+    /// the number of bytes the header occupies.
+    fn parse_cell_header(&self, offset: usize) -> (u32, u64, usize) {
+        let data = self.page.data();
+        let (total_len, n1) = parse_varint(&data[offset..]);
+        let (key, n2) = parse_varint(&data[offset + n1..]);
+        (total_len as u32, key, n1 + n2)
+    }
+
+    /// Read just the logical payload-length header for the cell at `index`,
+    /// without materializing its stored bytes. Equal to the cell's stored
+    /// payload length unless it overflowed, in which case it's the full
+    /// length reassembled by `BTree::read_cell_payload`.
+    fn cell_total_len(&self, index: u16) -> u32 {
+        let offset = self.pointer_at(index as usize) as usize;
+        self.parse_cell_header(offset).0
+    }
+
+    /// Binary-search the offset array for the index a cell with `key` should
+    /// be inserted at, so the array stays sorted by key.
+    fn find_insert_index(&self, key: u64) -> usize {
+        let mut lo = 0usize;
+        let mut hi = self.n_cells as usize;
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.cell_key_at(mid) < key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    /// For an `InternalTable` node, resolve the child page that should
+    /// contain `key`: the left child of the first cell whose key is
+    /// strictly greater than `key`, or `right_page` when no separator is
+    /// greater than `key`. A cell's left child only holds keys strictly
+    /// less than its separator, so a key equal to a separator belongs in
+    /// the subtree to its right (the next cell's left child, or
+    /// `right_page` if the matching separator is the last cell).
+    fn find_child(&self, key: u64) -> u32 {
+        let mut index = self.find_insert_index(key);
+        if index < self.n_cells as usize && self.cell_key_at(index) == key {
+            index += 1;
+        }
+
+        if index == self.n_cells as usize {
+            return self.right_page;
+        }
+
+        self.child_at(index as u16)
+    }
+
+    /// For an `InternalTable` node, the child page number stored in the
+    /// cell at `index`'s payload.
+    fn child_at(&self, index: u16) -> u32 {
+        let cell = self.get_cell(index);
+        let mut child_page = [0; 4];
+        child_page.copy_from_slice(&cell.payload[..4]);
+        u32::from_be_bytes(child_page)
+    }
+
+    /// Overwrite the child page number stored in the cell at `index`'s
+    /// payload in place, without touching its key or any other cell. Used
+    /// to repoint a parent's pointer at a sibling after a split relocates
+    /// the half it used to point at.
+    fn set_child_pointer(&mut self, index: u16, child_page: u32) {
+        let offset = self.pointer_at(index as usize) as usize;
+        let (_, _, header_size) = self.parse_cell_header(offset);
+        let payload_start = offset + header_size;
+        let data = self.page.data_as_mut();
+        data[payload_start..payload_start + 4].copy_from_slice(&child_page.to_be_bytes());
+    }
+
+    fn cell_key_at(&self, index: usize) -> u64 {
+        let offset = self.pointer_at(index) as usize;
+        self.parse_cell_header(offset).1
+    }
+
+    /// Resolve the `index`-th entry of the cell offset array to a byte
+    /// offset into the page.
+    fn pointer_at(&self, index: usize) -> u16 {
+        assert!(index < self.n_cells as usize, "cell index out of bounds");
+        let array_start = self.celloffset_array as usize + index * POINTER_SIZE as usize;
+        let data = self.page.data();
+        let mut offset = [0; 2];
+        offset.copy_from_slice(&data[array_start..array_start + 2]);
+        u16::from_be_bytes(offset)
+    }
 }
 
 const MAGIC_BYTES_SIZE: usize = 15;
@@ -398,7 +1098,7 @@ impl Default for BTreeHeader {
     fn default() -> Self {
         BTreeHeader {
             magic_bytes: MAGIC_BYTES.clone(),
-            page_size: PAGE_SIZE as u16,
+            page_size: DEFAULT_PAGE_SIZE as u16,
             file_change_counter: 0,
             schema_version: 0,
             page_cache_size: PAGE_CACHE_SIZE_INITIAL as u32,
@@ -448,6 +1148,70 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_write_node_right_page_survives_beyond_u16_range() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_write_node_right_page_survives_beyond_u16_range");
+
+        let mut btree = BTree::open(&file)?;
+        let mut node = btree.new_node(BTreeNodeType::InternalTable)?;
+        node.right_page = 100_000; // past u16::MAX, plausible once a table spans enough pages
+        btree.write_node(&mut node)?;
+
+        let updated_node = btree.get_node_by_page(node.page.n_page)?;
+        assert_eq!(
+            updated_node.right_page, 100_000,
+            "Expected a right_page beyond u16::MAX to round-trip without truncating"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_node_survives_pager_drop() -> Result<(), ChiError> {
+        let file = TempDir::new()?.into_path().join("test_write_node_survives_pager_drop");
+
+        {
+            let mut btree = BTree::open(&file)?;
+            let mut node = btree.new_node(BTreeNodeType::InternalTable)?;
+            node.free_offset += 1;
+            btree.write_node(&mut node)?;
+        } // btree (and its Pager) drops here, flushing dirty pages to disk
+
+        let mut reopened = BTree::open(&file)?;
+        let node = reopened.get_node_by_page(2)?;
+        assert_eq!(
+            node.free_offset,
+            PAGE_HEADER_SIZE as u16 + 1,
+            "Expected write to survive pager drop"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_converts_header_page_cache_size_bytes_to_pages() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_open_converts_header_page_cache_size_bytes_to_pages");
+
+        let btree = BTree::open(&file)?;
+
+        // PAGE_CACHE_SIZE_INITIAL is a byte budget, not a page count, so the
+        // resulting cache capacity must be far smaller than the raw value.
+        assert!(
+            btree.pager.cache_capacity() < PAGE_CACHE_SIZE_INITIAL,
+            "Expected cache capacity to be converted from bytes to pages"
+        );
+        assert_eq!(
+            btree.pager.cache_capacity(),
+            PAGE_CACHE_SIZE_INITIAL / btree.pager.page_size()
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_create_new_node() -> Result<(), ChiError> {
         let file = TempDir::new()?.into_path().join("test_create_new_node");
@@ -457,9 +1221,9 @@ mod tests {
 
         assert_eq!(node.page.n_page, 2);
         assert_eq!(node.typ, BTreeNodeType::InternalTable);
-        assert_eq!(node.free_offset, 0);
+        assert_eq!(node.free_offset, PAGE_HEADER_SIZE as u16);
         assert_eq!(node.n_cells, 0);
-        assert_eq!(node.cells_offset, PAGE_SIZE as u16);
+        assert_eq!(node.cells_offset, DEFAULT_PAGE_SIZE as u16);
         assert_eq!(node.right_page, 0);
         assert_eq!(node.celloffset_array, PAGE_HEADER_SIZE);
 
@@ -491,6 +1255,367 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_insert_cell_and_get_cell_roundtrip() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_insert_cell_and_get_cell_roundtrip");
+
+        let mut btree = BTree::open(&file)?;
+        let mut node = btree.new_node(BTreeNodeType::LeafTable)?;
+
+        node.insert_cell(10, b"ten")?;
+        node.insert_cell(5, b"five")?;
+        node.insert_cell(20, b"twenty")?;
+
+        assert_eq!(node.n_cells, 3);
+
+        // The offset array must stay sorted by key regardless of insertion order.
+        assert_eq!(
+            node.get_cell(0),
+            Cell {
+                key: 5,
+                payload: b"five".to_vec()
+            }
+        );
+        assert_eq!(
+            node.get_cell(1),
+            Cell {
+                key: 10,
+                payload: b"ten".to_vec()
+            }
+        );
+        assert_eq!(
+            node.get_cell(2),
+            Cell {
+                key: 20,
+                payload: b"twenty".to_vec()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_cell_rejects_when_node_is_full() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_insert_cell_rejects_when_node_is_full");
+
+        let mut btree = BTree::open(&file)?;
+        let mut node = btree.new_node(BTreeNodeType::LeafTable)?;
+
+        let payload = vec![0u8; DEFAULT_PAGE_SIZE];
+        let result = node.insert_cell(1, &payload);
+
+        assert_eq!(result.err(), Some(ChiError::Enospace));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_cell_reclaims_space_for_later_inserts() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_delete_cell_reclaims_space_for_later_inserts");
+
+        let mut btree = BTree::open(&file)?;
+        let mut node = btree.new_node(BTreeNodeType::LeafTable)?;
+
+        node.insert_cell(1, b"hello world")?;
+        let free_size_before_delete = node.compute_free_size();
+
+        node.delete_cell(0)?;
+        assert_eq!(
+            node.compute_free_size(),
+            free_size_before_delete
+                + cell_header_size("hello world".len() as u64, 1) as u16
+                + "hello world".len() as u16
+                + POINTER_SIZE,
+            "Expected deleted cell space to be reclaimed"
+        );
+
+        // Re-inserting a cell that fits in the freeblock must not grow cells_offset.
+        let cells_offset_before_insert = node.cells_offset;
+        node.insert_cell(2, b"hello world")?;
+        assert_eq!(
+            node.cells_offset, cells_offset_before_insert,
+            "Expected cell content to be served from the freeblock list"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_splits_root_leaf_into_internal_table() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_insert_splits_root_leaf_into_internal_table");
+
+        let mut btree = BTree::open(&file)?;
+
+        let payload = vec![0u8; 100];
+        let mut key = 0u64;
+        loop {
+            key += 1;
+            btree.insert(key, &payload)?;
+
+            let root = btree.get_node_by_page(1)?;
+            if root.typ == BTreeNodeType::InternalTable {
+                break;
+            }
+            assert!(key <= 1000, "root never split after 1000 inserts");
+        }
+
+        // Every inserted key should still be reachable after the split.
+        for probe in [1, key / 2, key] {
+            let leaf_page = btree.find_leaf(probe)?;
+            let leaf = btree.get_node_by_page(leaf_page)?;
+            assert_eq!(leaf.typ, BTreeNodeType::LeafTable);
+            let found = (0..leaf.n_cells).any(|i| leaf.get_cell(i).key == probe);
+            assert!(found, "Expected key {} to be present after split", probe);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_leaf_resolves_exact_separator_key_to_right_child() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_find_leaf_resolves_exact_separator_key_to_right_child");
+
+        let mut btree = BTree::open(&file)?;
+
+        let payload = vec![0u8; 100];
+        let mut key = 0u64;
+        loop {
+            key += 1;
+            btree.insert(key, &payload)?;
+
+            let root = btree.get_node_by_page(1)?;
+            if root.typ == BTreeNodeType::InternalTable {
+                break;
+            }
+            assert!(key <= 1000, "root never split after 1000 inserts");
+        }
+
+        // The separator key itself was promoted into the parent, not moved
+        // into the left child, so it must resolve to the subtree on the
+        // right of the cell it lives in.
+        let root = btree.get_node_by_page(1)?;
+        let separator_key = root.get_cell(0).key;
+
+        let leaf_page = btree.find_leaf(separator_key)?;
+        let leaf = btree.get_node_by_page(leaf_page)?;
+        let found = (0..leaf.n_cells).any(|i| leaf.get_cell(i).key == separator_key);
+        assert!(
+            found,
+            "Expected find_leaf({separator_key}) to resolve to the leaf actually holding it"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_survives_non_root_split() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_insert_survives_non_root_split");
+
+        let mut btree = BTree::open(&file)?;
+
+        // Insert enough keys to force the root to split, then keep going
+        // well past that so a leaf other than the old root has to split
+        // too, promoting a separator into an existing InternalTable parent.
+        let payload = vec![0u8; 100];
+        let mut key = 0u64;
+        let mut root_split_at = None;
+        loop {
+            key += 1;
+            btree.insert(key, &payload)?;
+
+            let root = btree.get_node_by_page(1)?;
+            if root.typ == BTreeNodeType::InternalTable && root_split_at.is_none() {
+                root_split_at = Some(key);
+            }
+            if let Some(first_split) = root_split_at {
+                if key >= first_split * 3 {
+                    break;
+                }
+            }
+            assert!(key <= 10_000, "root never split after 10000 inserts");
+        }
+
+        for probe in 1..=key {
+            let leaf_page = btree.find_leaf(probe)?;
+            let leaf = btree.get_node_by_page(leaf_page)?;
+            assert_eq!(leaf.typ, BTreeNodeType::LeafTable);
+            let found = (0..leaf.n_cells).any(|i| leaf.get_cell(i).key == probe);
+            assert!(
+                found,
+                "Expected key {} to still be reachable after a non-root split",
+                probe
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_and_read_overflowing_payload() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_insert_and_read_overflowing_payload");
+
+        let mut btree = BTree::open(&file)?;
+
+        // A few pages' worth of payload, enough to span multiple overflow pages.
+        let payload: Vec<u8> = (0..(DEFAULT_PAGE_SIZE * 3)).map(|i| (i % 251) as u8).collect();
+        btree.insert(1, &payload)?;
+
+        let leaf_page = btree.find_leaf(1)?;
+        let leaf = btree.get_node_by_page(leaf_page)?;
+        assert_eq!(leaf.n_cells, 1);
+
+        let reassembled = btree.read_cell_payload(&leaf, 0)?;
+        assert_eq!(reassembled, payload, "Expected full payload to survive the overflow chain");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_bumps_file_change_counter() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_commit_bumps_file_change_counter");
+
+        let mut btree = BTree::open(&file)?;
+        let counter_before = btree.read_header()?.file_change_counter;
+
+        btree.insert(1, b"hello world")?;
+
+        let counter_after = btree.read_header()?.file_change_counter;
+        assert_eq!(
+            counter_after,
+            counter_before + 1,
+            "Expected a committed insert to bump file_change_counter"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_bumps_file_change_counter_across_many_commits() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_commit_bumps_file_change_counter_across_many_commits");
+
+        let mut btree = BTree::open(&file)?;
+        let counter_before = btree.read_header()?.file_change_counter;
+
+        for key in 1..=10u64 {
+            btree.insert(key, b"hello world")?;
+        }
+
+        let counter_after = btree.read_header()?.file_change_counter;
+        assert_eq!(
+            counter_after,
+            counter_before + 10,
+            "Expected file_change_counter to advance on every commit, not just the first"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollback_restores_page_to_pre_transaction_contents() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_rollback_restores_page_to_pre_transaction_contents");
+
+        let mut btree = BTree::open(&file)?;
+        btree.insert(1, b"hello world")?;
+        let free_offset_before = btree.get_node_by_page(1)?.free_offset;
+
+        btree.begin()?;
+        let mut node = btree.get_node_by_page(1)?;
+        node.free_offset += 1;
+        btree.write_node(&mut node)?;
+        assert_eq!(btree.get_node_by_page(1)?.free_offset, free_offset_before + 1);
+
+        btree.rollback()?;
+
+        assert_eq!(
+            btree.get_node_by_page(1)?.free_offset,
+            free_offset_before,
+            "Expected rollback to undo the in-transaction write"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollback_discards_pages_allocated_mid_transaction() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_rollback_discards_pages_allocated_mid_transaction");
+
+        let mut btree = BTree::open(&file)?;
+        btree.insert(1, b"hello world")?;
+
+        btree.begin()?;
+        let n_page = btree.pager.allocate_page();
+        let data = vec![0xAB; btree.pager.page_size()];
+        btree.pager.write_page(&MemPage::new(n_page, &data, 0))?;
+        btree.rollback()?;
+
+        // The allocated page's dirty cache entry must not survive the
+        // rollback, or this flush would write it straight back to disk and
+        // re-extend the file past its pre-transaction size.
+        btree.pager.flush()?;
+
+        let file_len = std::fs::metadata(&file)?.len();
+        let expected_len = btree.pager.page_size() as u64;
+        assert_eq!(
+            file_len, expected_len,
+            "Expected flush after rollback not to re-extend the file with a discarded page"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reopen_recovers_uncommitted_transaction_left_by_a_crash() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_reopen_recovers_uncommitted_transaction_left_by_a_crash");
+
+        let free_offset_before;
+        {
+            let mut btree = BTree::open(&file)?;
+            btree.insert(1, b"hello world")?;
+            free_offset_before = btree.get_node_by_page(1)?.free_offset;
+
+            // Simulate a crash mid-transaction: the journal is written, but
+            // neither `commit` nor `rollback` ever runs before `btree` (and
+            // its `Pager`) is dropped.
+            btree.begin()?;
+            let mut node = btree.get_node_by_page(1)?;
+            node.free_offset += 1;
+            btree.write_node(&mut node)?;
+        }
+
+        let mut reopened = BTree::open(&file)?;
+        assert_eq!(
+            reopened.get_node_by_page(1)?.free_offset,
+            free_offset_before,
+            "Expected reopening to roll back the journal left by the crash"
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_first_node_page_leaf_table() -> Result<(), ChiError> {
         let file = TempDir::new()?.into_path().join("test_create_new_node");
@@ -540,4 +1665,50 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_pager_honors_custom_page_size_from_header() -> Result<(), ChiError> {
+        let dir = TempDir::new()?.into_path();
+
+        for page_size in [4096u16, 8192, 16384] {
+            let path = dir.join(format!("test_pager_custom_page_size_{page_size}"));
+
+            let mut pager = pager::Pager::open(&path)?;
+            let header = BTreeHeader {
+                page_size,
+                ..Default::default()
+            };
+            pager.write_header(&header.to_bytes()?)?;
+            drop(pager);
+
+            let reopened = pager::Pager::open(&path)?;
+            assert_eq!(
+                reopened.page_size(),
+                page_size as usize,
+                "Expected a {page_size}-byte page size to round-trip through the header"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pager_rejects_non_power_of_two_page_size() -> Result<(), ChiError> {
+        let file = TempDir::new()?
+            .into_path()
+            .join("test_pager_rejects_non_power_of_two_page_size");
+
+        let mut pager = pager::Pager::open(&file)?;
+        let header = BTreeHeader {
+            page_size: 5000,
+            ..Default::default()
+        };
+        pager.write_header(&header.to_bytes()?)?;
+        drop(pager);
+
+        let result = pager::Pager::open(&file);
+        assert_eq!(result.err(), Some(ChiError::Ecorruptheader));
+
+        Ok(())
+    }
 }
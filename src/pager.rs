@@ -1,17 +1,55 @@
 use bytes::BytesMut;
-use std::fs::{File, OpenOptions};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
 use std::io::{
     self,
     prelude::{Read, Seek, Write},
     SeekFrom,
 };
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::ChiError;
 
-pub const PAGE_SIZE: usize = 4096 * 4; // 8 Kb
+/// Page size used to initialize a brand-new database file. Existing files
+/// carry their own page size in the header (see `Pager::open`), so this is
+/// only ever a starting point, not a hard limit on what the pager can serve.
+pub const DEFAULT_PAGE_SIZE: usize = 4096 * 4; // 16 Kb
 pub const HEADER_SIZE: usize = 100;
 
+/// Smallest and largest page size `Pager::open` will accept from a file's
+/// header. Page size must also be a power of two; the upper bound keeps it
+/// representable in the header's 16-bit field.
+const MIN_PAGE_SIZE: u32 = 512;
+const MAX_PAGE_SIZE: u32 = 32768;
+
+/// Byte offset of the page-size field within the file header: right after
+/// the 15-byte magic string.
+const PAGE_SIZE_OFFSET: usize = 15;
+
+/// Default number of pages kept in the pager's in-memory cache, used until
+/// `Pager::set_cache_capacity` is called with a page count derived from the
+/// cache size (in bytes) stored in the database header.
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// Path of the rollback journal for a database file, sitting next to it
+/// with a `-journal` suffix, the way SQLite itself names its journals.
+fn journal_path_for(filename: &Path) -> PathBuf {
+    let mut name = filename.as_os_str().to_owned();
+    name.push("-journal");
+    PathBuf::from(name)
+}
+
+/// A page held in the pager's cache.
+///
+/// `dirty` tracks whether the in-memory copy has been modified since it was
+/// last flushed to disk, so clean pages can be evicted without writing them
+/// back.
+#[derive(Debug)]
+struct CachedPage {
+    data: Vec<u8>,
+    dirty: bool,
+}
+
 /// Represents a in-memory copy of page
 pub struct MemPage {
     /// Number of physical page
@@ -26,9 +64,9 @@ pub struct MemPage {
 
 impl MemPage {
     /// Create a new MemPage
-    pub fn new(n_page: u32, raw: [u8; PAGE_SIZE], offset: u16) -> Self {
+    pub fn new(n_page: u32, raw: &[u8], offset: u16) -> Self {
         let mut data = BytesMut::with_capacity(raw.len());
-        data.extend_from_slice(&raw);
+        data.extend_from_slice(raw);
         MemPage {
             n_page,
             data,
@@ -68,6 +106,41 @@ impl MemPage {
 pub struct Pager {
     buffer: File,
     total_pages: u32,
+
+    /// Size, in bytes, of every page in this database file. Read from the
+    /// file header on `open` (and validated as a power of two in range), or
+    /// set to `DEFAULT_PAGE_SIZE` when initializing a brand-new file.
+    page_size: u32,
+
+    /// Pages currently held in memory, keyed by page number.
+    cache: HashMap<u32, CachedPage>,
+
+    /// Page numbers in least-to-most-recently-used order; the front is the
+    /// next eviction candidate.
+    cache_order: VecDeque<u32>,
+
+    /// Maximum number of pages `cache` is allowed to hold before the
+    /// least-recently-used clean page is evicted.
+    cache_capacity: usize,
+
+    /// Path of the sidecar rollback journal, sitting next to the database
+    /// file itself.
+    journal_path: PathBuf,
+
+    /// Open handle to the journal file while a transaction is in progress.
+    /// `None` until the first page is actually journaled, since a
+    /// transaction that never modifies a page should never create a file.
+    journal_file: Option<File>,
+
+    /// `total_pages` as it was when the current transaction began, so
+    /// `rollback` knows how far to truncate the database file back to
+    /// discard pages allocated mid-transaction. `None` outside a
+    /// transaction.
+    transaction_total_pages: Option<u32>,
+
+    /// Page numbers already journaled in the current transaction, so a page
+    /// written to more than once only has its *original* contents recorded.
+    journaled_pages: HashSet<u32>,
 }
 
 impl Pager {
@@ -78,17 +151,310 @@ impl Pager {
     // Parameters
     // - filename: Database file (might not exist)
     pub fn open(filename: &Path) -> Result<Pager, ChiError> {
-        let buffer = OpenOptions::new()
+        let mut buffer = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
             .open(filename)?;
+        let file_len = buffer.metadata()?.len();
+
+        let page_size = if file_len == 0 {
+            DEFAULT_PAGE_SIZE as u32
+        } else {
+            Self::read_page_size_from_header(&mut buffer)?
+        };
+
+        let total_pages = (file_len / page_size as u64) as u32;
+        let journal_path = journal_path_for(filename);
         Ok(Pager {
             buffer,
-            total_pages: 0,
+            total_pages,
+            page_size,
+            cache: HashMap::new(),
+            cache_order: VecDeque::new(),
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            journal_path,
+            journal_file: None,
+            transaction_total_pages: None,
+            journaled_pages: HashSet::new(),
         })
     }
 
+    /// Read and validate the page size stored in an existing file's header,
+    /// without needing a fully-constructed `Pager` yet.
+    fn read_page_size_from_header(buffer: &mut File) -> Result<u32, ChiError> {
+        buffer.seek(SeekFrom::Start(0))?;
+        let mut header = [0; HEADER_SIZE];
+        buffer.read_exact(&mut header)?;
+
+        let mut raw = [0; 2];
+        raw.copy_from_slice(&header[PAGE_SIZE_OFFSET..PAGE_SIZE_OFFSET + 2]);
+        let page_size = u16::from_le_bytes(raw) as u32;
+
+        if !page_size.is_power_of_two() || !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+            return Err(ChiError::Ecorruptheader);
+        }
+
+        Ok(page_size)
+    }
+
+    /// Size, in bytes, of every page in this database.
+    pub fn page_size(&self) -> usize {
+        self.page_size as usize
+    }
+
+    /// If a rollback journal is present (left behind by a transaction that
+    /// never committed, e.g. a crash), replay it into the database and
+    /// remove it. Returns whether a journal was found.
+    ///
+    /// Must be called before the database is otherwise read, so callers see
+    /// a consistent pre-crash state rather than a half-written transaction.
+    pub fn recover_from_journal(&mut self) -> Result<bool, ChiError> {
+        if !self.journal_path.exists() {
+            return Ok(false);
+        }
+
+        self.apply_journal_file()?;
+        let _ = fs::remove_file(&self.journal_path);
+        Ok(true)
+    }
+
+    /// Start recording a transaction: the first time each page is modified
+    /// from here on, its pre-transaction contents are journaled before the
+    /// write goes through. Calling this while already in a transaction is a
+    /// no-op.
+    pub fn begin_transaction(&mut self) -> Result<(), ChiError> {
+        if self.transaction_total_pages.is_some() {
+            return Ok(());
+        }
+
+        self.transaction_total_pages = Some(self.total_pages);
+        self.journaled_pages.clear();
+        self.journal_file = None;
+        Ok(())
+    }
+
+    /// Make the current transaction's writes durable: flush dirty pages,
+    /// fsync the database file, then discard the journal. A no-op outside a
+    /// transaction.
+    pub fn commit(&mut self) -> Result<(), ChiError> {
+        if self.transaction_total_pages.is_none() {
+            return Ok(());
+        }
+
+        self.flush()?;
+        self.buffer.sync_all()?;
+
+        self.journal_file = None;
+        let _ = fs::remove_file(&self.journal_path);
+        self.transaction_total_pages = None;
+        self.journaled_pages.clear();
+
+        Ok(())
+    }
+
+    /// Undo the current transaction: replay the journaled pages back into
+    /// the database, drop any pages allocated since `begin_transaction`, and
+    /// discard the journal. A no-op outside a transaction.
+    pub fn rollback(&mut self) -> Result<(), ChiError> {
+        if self.transaction_total_pages.is_none() {
+            return Ok(());
+        }
+
+        self.journal_file = None;
+        if self.journal_path.exists() {
+            self.apply_journal_file()?;
+        } else {
+            // No page was modified yet, so the only thing to undo is any
+            // page allocated (but never written) during the transaction.
+            self.discard_pages_beyond(self.transaction_total_pages.unwrap())?;
+        }
+        let _ = fs::remove_file(&self.journal_path);
+
+        self.transaction_total_pages = None;
+        self.journaled_pages.clear();
+
+        Ok(())
+    }
+
+    /// Reset `total_pages` back to `total_pages_before`, truncate the file to
+    /// match, and drop every cache entry for a page beyond that count —
+    /// whether or not it was ever journaled. A page allocated mid-transaction
+    /// is never journaled (there's nothing to restore it to), so without this
+    /// its dirty cache entry would outlive the rollback and get written
+    /// straight back to disk by the next flush, eviction, or `Drop`,
+    /// re-extending the file past the rolled-back size.
+    fn discard_pages_beyond(&mut self, total_pages_before: u32) -> Result<(), ChiError> {
+        self.cache.retain(|&n_page, _| n_page <= total_pages_before);
+        self.cache_order.retain(|&n_page| n_page <= total_pages_before);
+
+        self.total_pages = total_pages_before;
+        self.buffer
+            .set_len(total_pages_before as u64 * self.page_size() as u64)?;
+        self.buffer.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Before the first write to `n_page` within the current transaction,
+    /// record its pre-transaction contents in the journal. Pages allocated
+    /// during the transaction itself (page numbers past the pre-transaction
+    /// `total_pages`) have nothing to preserve, since rollback discards them
+    /// by truncating the file instead.
+    fn journal_page_if_needed(&mut self, n_page: u32) -> Result<(), ChiError> {
+        let total_pages_before = match self.transaction_total_pages {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+
+        if n_page > total_pages_before || self.journaled_pages.contains(&n_page) {
+            return Ok(());
+        }
+
+        let original = match self.cache.get(&n_page) {
+            Some(cached) => cached.data.clone(),
+            None => self.read_raw_page(n_page)?,
+        };
+
+        self.append_journal_record(n_page, &original)?;
+        self.journaled_pages.insert(n_page);
+
+        Ok(())
+    }
+
+    /// Append a `(page number, original page bytes)` record to the journal
+    /// file, creating it (and writing its header) on the first call of a
+    /// transaction.
+    fn append_journal_record(&mut self, n_page: u32, data: &[u8]) -> Result<(), ChiError> {
+        if self.journal_file.is_none() {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&self.journal_path)?;
+            file.write_all(&self.transaction_total_pages.unwrap().to_le_bytes())?;
+            self.journal_file = Some(file);
+        }
+
+        let file = self.journal_file.as_mut().expect("just created above");
+        file.write_all(&n_page.to_le_bytes())?;
+        file.write_all(data)?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Replay every record in the on-disk journal file into the database,
+    /// then truncate the file back to the page count the journal's header
+    /// says the transaction started with.
+    fn apply_journal_file(&mut self) -> Result<(), ChiError> {
+        let mut journal = File::open(&self.journal_path)?;
+
+        let mut total_pages_before = [0; 4];
+        journal.read_exact(&mut total_pages_before)?;
+        let total_pages_before = u32::from_le_bytes(total_pages_before);
+
+        let mut n_page_bytes = [0; 4];
+        loop {
+            match journal.read_exact(&mut n_page_bytes) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            }
+            let n_page = u32::from_le_bytes(n_page_bytes);
+
+            let mut data = vec![0; self.page_size()];
+            journal.read_exact(&mut data)?;
+
+            self.write_through(n_page, &data)?;
+            self.cache.remove(&n_page);
+        }
+
+        // Pages allocated mid-transaction but never journaled (nothing to
+        // restore them to) still need their cache entries dropped and the
+        // file truncated back, or they reappear on the next flush.
+        self.discard_pages_beyond(total_pages_before)?;
+
+        Ok(())
+    }
+
+    /// Read a page directly from disk, bypassing the cache.
+    fn read_raw_page(&mut self, n_page: u32) -> Result<Vec<u8>, ChiError> {
+        let seek = (n_page - 1) * self.page_size;
+        self.buffer.seek(SeekFrom::Start(seek as u64))?;
+        let mut data = vec![0; self.page_size()];
+        self.buffer.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Configure the maximum number of pages kept in the in-memory cache.
+    ///
+    /// If the cache is already over the new capacity, least-recently-used
+    /// clean pages are evicted (and dirty ones flushed) immediately.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache_capacity = capacity.max(1);
+        let _ = self.evict_if_needed();
+    }
+
+    /// Maximum number of pages the in-memory cache is currently allowed to hold.
+    pub fn cache_capacity(&self) -> usize {
+        self.cache_capacity
+    }
+
+    /// Write every dirty cached page back to disk without evicting it from
+    /// the cache.
+    pub fn flush(&mut self) -> Result<(), ChiError> {
+        let dirty_pages: Vec<u32> = self
+            .cache
+            .iter()
+            .filter(|(_, entry)| entry.dirty)
+            .map(|(&n_page, _)| n_page)
+            .collect();
+
+        for n_page in dirty_pages {
+            let data = self.cache[&n_page].data.clone();
+            self.write_through(n_page, &data)?;
+            if let Some(entry) = self.cache.get_mut(&n_page) {
+                entry.dirty = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move `n_page` to the most-recently-used end of the eviction order.
+    fn touch(&mut self, n_page: u32) {
+        self.cache_order.retain(|&p| p != n_page);
+        self.cache_order.push_back(n_page);
+    }
+
+    /// Evict least-recently-used pages, flushing dirty ones first, until the
+    /// cache is back within `cache_capacity`.
+    fn evict_if_needed(&mut self) -> Result<(), ChiError> {
+        while self.cache.len() > self.cache_capacity {
+            let victim = match self.cache_order.pop_front() {
+                Some(n_page) => n_page,
+                None => break,
+            };
+
+            if let Some(entry) = self.cache.remove(&victim) {
+                if entry.dirty {
+                    self.write_through(victim, &entry.data)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a page's raw bytes directly to disk, bypassing the cache.
+    fn write_through(&mut self, n_page: u32, data: &[u8]) -> Result<(), ChiError> {
+        let seek = (n_page - 1) * self.page_size;
+        self.buffer.seek(SeekFrom::Start(seek as u64))?;
+        self.buffer.write(data)?;
+        Ok(())
+    }
+
     /// Read the file header
     ///
     /// This function reads in the header of a chidb file and returns it
@@ -107,6 +473,16 @@ impl Pager {
     pub fn write_header(&mut self, header: &[u8; HEADER_SIZE]) -> Result<(), ChiError> {
         self.buffer.seek(SeekFrom::Start(0))?;
         self.buffer.write(header)?;
+
+        // Page 1 physically carries the header in its first HEADER_SIZE
+        // bytes (see the `offset` page one gets in `read_page`). If page 1
+        // is already cached, its copy of those bytes must be refreshed here
+        // too, or the next flush of that cached page would write its stale
+        // header bytes straight back over what we just wrote.
+        if let Some(cached) = self.cache.get_mut(&1) {
+            cached.data[..HEADER_SIZE].copy_from_slice(header);
+        }
+
         Ok(())
     }
 
@@ -133,12 +509,6 @@ impl Pager {
         if n_page > self.total_pages || n_page <= 0 {
             return Err(ChiError::EPageNo);
         }
-        let seek = (n_page - 1) * PAGE_SIZE as u32;
-        self.buffer.seek(SeekFrom::Start(seek as u64))?;
-
-        let mut data = [0; PAGE_SIZE];
-        let count = self.buffer.read(&mut data)?;
-        println!("Read {} bytes from page {}", count, n_page);
 
         // Page one is special, the first HEADER_SIZE are used by the header
         // so we start to read after the header.
@@ -148,17 +518,45 @@ impl Pager {
             offset = HEADER_SIZE as u16 + 1;
         }
 
-        Ok(MemPage::new(n_page, data, offset))
+        if let Some(cached) = self.cache.get(&n_page) {
+            let data = cached.data.clone();
+            self.touch(n_page);
+            return Ok(MemPage::new(n_page, &data, offset));
+        }
+
+        let seek = (n_page - 1) * self.page_size;
+        self.buffer.seek(SeekFrom::Start(seek as u64))?;
+
+        let mut data = vec![0; self.page_size()];
+        self.buffer.read(&mut data)?;
+
+        self.cache.insert(
+            n_page,
+            CachedPage {
+                data: data.clone(),
+                dirty: false,
+            },
+        );
+        self.touch(n_page);
+        self.evict_if_needed()?;
+
+        Ok(MemPage::new(n_page, &data, offset))
     }
 
     pub fn write_page(&mut self, page: &MemPage) -> Result<(), ChiError> {
         if page.n_page > self.total_pages || page.n_page <= 0 {
             return Err(ChiError::EPageNo);
         }
-        let seek = (page.n_page - 1) * PAGE_SIZE as u32;
-        self.buffer.seek(SeekFrom::Start(seek as u64))?;
-        let count = self.buffer.write(&page.data)?;
-        println!("Wrote {} bytes to page {}", count, page.n_page);
+
+        self.journal_page_if_needed(page.n_page)?;
+
+        let data = page.raw().to_vec();
+
+        self.cache
+            .insert(page.n_page, CachedPage { data, dirty: true });
+        self.touch(page.n_page);
+        self.evict_if_needed()?;
+
         Ok(())
     }
 
@@ -167,3 +565,9 @@ impl Pager {
         Ok(size == 0)
     }
 }
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
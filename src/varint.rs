@@ -0,0 +1,129 @@
+//! SQLite-style variable-length integer encoding: a big-endian base-128
+//! encoding that packs small values into a single byte while still
+//! supporting the full 64-bit range.
+//!
+//! Each of the first 8 bytes carries 7 bits of the value, high bit set to
+//! signal that another byte follows; the 9th byte (only ever reached for
+//! values needing more than 56 bits) carries the remaining 8 bits verbatim.
+
+/// Write `value` to `buf` (which must have at least 9 bytes available) and
+/// return the number of bytes written.
+pub fn write_varint(buf: &mut [u8], value: u64) -> usize {
+    // Values needing more than 56 bits always take the full 9-byte form:
+    // the last byte holds the low 8 bits verbatim, with no continuation
+    // bit, and the 8 bytes before it each carry 7 bits of the rest.
+    if value & 0xff00_0000_0000_0000 != 0 {
+        buf[8] = value as u8;
+        let mut v = value >> 8;
+        for i in (0..8).rev() {
+            buf[i] = ((v & 0x7f) | 0x80) as u8;
+            v >>= 7;
+        }
+        return 9;
+    }
+
+    let mut chunks = [0u8; 9];
+    let mut n = 0;
+    let mut v = value;
+    loop {
+        chunks[n] = ((v & 0x7f) | 0x80) as u8;
+        n += 1;
+        v >>= 7;
+        if v == 0 {
+            break;
+        }
+    }
+    chunks[0] &= 0x7f; // no continuation bit on the final (least significant) byte
+
+    for (i, j) in (0..n).rev().enumerate() {
+        buf[i] = chunks[j];
+    }
+    n
+}
+
+/// Number of bytes `write_varint` would need to encode `value`, without
+/// actually writing anything.
+pub fn varint_size(value: u64) -> usize {
+    if value & 0xff00_0000_0000_0000 != 0 {
+        return 9;
+    }
+
+    let mut n = 1;
+    let mut v = value >> 7;
+    while v != 0 {
+        n += 1;
+        v >>= 7;
+    }
+    n
+}
+
+/// Decode a varint from the start of `buf`, returning the value and the
+/// number of bytes consumed. Stops after 9 bytes even if the 9th byte's
+/// high bit is set, since that byte always contributes all 8 of its bits.
+pub fn parse_varint(buf: &[u8]) -> (u64, usize) {
+    let mut result: u64 = 0;
+    for (i, &byte) in buf.iter().enumerate().take(8) {
+        result = (result << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return (result, i + 1);
+        }
+    }
+
+    result = (result << 8) | buf[8] as u64;
+    (result, 9)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_small_values() {
+        for value in [0u64, 1, 63, 127, 128, 129, 300, 16383, 16384] {
+            let mut buf = [0u8; 9];
+            let written = write_varint(&mut buf, value);
+            assert_eq!(written, varint_size(value));
+
+            let (decoded, consumed) = parse_varint(&buf);
+            assert_eq!(consumed, written);
+            assert_eq!(decoded, value);
+        }
+    }
+
+    #[test]
+    fn test_single_byte_values_decode_directly() {
+        let mut buf = [0u8; 9];
+        write_varint(&mut buf, 42);
+        assert_eq!(buf[0], 42);
+        assert_eq!(parse_varint(&buf), (42, 1));
+    }
+
+    #[test]
+    fn test_roundtrip_full_width_values() {
+        for value in [u64::MAX, u64::MAX - 1, 1u64 << 63, 0x00ff_ffff_ffff_ffff] {
+            let mut buf = [0u8; 9];
+            let written = write_varint(&mut buf, value);
+
+            let (decoded, consumed) = parse_varint(&buf);
+            assert_eq!(consumed, written);
+            assert_eq!(decoded, value);
+        }
+    }
+
+    #[test]
+    fn test_nine_byte_encoding_used_above_56_bits() {
+        let mut buf = [0u8; 9];
+        assert_eq!(write_varint(&mut buf, 1u64 << 56), 9);
+        assert_eq!(varint_size(1u64 << 56), 9);
+        assert_eq!(write_varint(&mut buf, (1u64 << 56) - 1), 8);
+    }
+
+    #[test]
+    fn test_parse_stops_at_nine_bytes_even_with_continuation_bit_set() {
+        // All nine bytes have their high bit set; a buggy decoder that kept
+        // reading past the 9th byte would run off the end of this buffer.
+        let buf = [0xffu8; 9];
+        let (_, consumed) = parse_varint(&buf);
+        assert_eq!(consumed, 9);
+    }
+}